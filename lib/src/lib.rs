@@ -8,7 +8,7 @@ pub use jsonrpc_client_macro::*;
 pub const V1: &'static str = "1.0";
 pub const V2: &'static str = "2.0";
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(untagged)]
 pub enum Id {
     Number(i64),
@@ -17,37 +17,119 @@ pub enum Id {
 
 #[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct Request {
-    pub id: Id,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Id>,
     pub jsonrpc: &'static str,
     pub method: String,
-    pub params: Vec<serde_json::Value>,
+    pub params: Params,
 }
 
 impl Request {
-    pub fn new_v2(method: &str, params: Vec<serde_json::Value>) -> Self {
+    pub fn new_v2(id: Id, method: &str, params: Vec<serde_json::Value>) -> Self {
+        Self {
+            id: Some(id),
+            jsonrpc: V2,
+            method: method.to_owned(),
+            params: Params::Positional(params),
+        }
+    }
+
+    pub fn new_v2_named(
+        id: Id,
+        method: &str,
+        params: serde_json::Map<String, serde_json::Value>,
+    ) -> Self {
+        Self {
+            id: Some(id),
+            jsonrpc: V2,
+            method: method.to_owned(),
+            params: Params::Named(params),
+        }
+    }
+
+    /// Builds a JSON-RPC 2.0 notification: a request with no `id`, to which
+    /// the server must not reply.
+    pub fn new_v2_notification(method: &str, params: Vec<serde_json::Value>) -> Self {
         Self {
-            id: Id::Number(0),
+            id: None,
             jsonrpc: V2,
             method: method.to_owned(),
-            params,
+            params: Params::Positional(params),
         }
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+/// The two shapes JSON-RPC 2.0 allows for a request's `params`: a positional
+/// array or a by-name object.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Params {
+    Positional(Vec<serde_json::Value>),
+    Named(serde_json::Map<String, serde_json::Value>),
+}
+
+#[derive(Debug, PartialEq)]
 pub struct Response {
     pub id: Id,
     pub jsonrpc: &'static str,
-    #[serde(flatten)]
     pub payload: ResponsePayload,
 }
 
+// `#[serde(flatten)]` can't be used to spread `payload`'s `result`/`error`
+// members back onto `Response` here: serde's flatten support buffers the
+// flattened fields through an intermediate representation that doesn't
+// preserve `RawValue`'s unparsed JSON, so `result` would get parsed (and
+// its zero-copy benefit lost) or fail to deserialize at all. Deserializing
+// through a plain, unflattened helper sidesteps that.
+impl<'de> Deserialize<'de> for Response {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        // `jsonrpc` is read as an owned `String` and discarded (it's always
+        // "2.0") rather than `&'static str`: borrowing into the input would
+        // force `'de: 'static` on this whole impl, making `Response`
+        // undeserializable from any non-`'static` input — i.e. every real
+        // HTTP response body.
+        #[derive(Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        struct Helper {
+            id: Id,
+            #[allow(dead_code)]
+            jsonrpc: String,
+            #[serde(default)]
+            result: Option<Box<serde_json::value::RawValue>>,
+            #[serde(default)]
+            error: Option<JsonRpcError>,
+        }
+
+        let helper = Helper::deserialize(deserializer)?;
+        let payload = match (helper.result, helper.error) {
+            (Some(result), None) => ResponsePayload::Result(result),
+            (None, Some(error)) => ResponsePayload::Error(error),
+            _ => {
+                return Err(serde::de::Error::custom(
+                    "response must have exactly one of `result` or `error`",
+                ))
+            }
+        };
+
+        Ok(Response {
+            id: helper.id,
+            jsonrpc: V2,
+            payload,
+        })
+    }
+}
+
 impl Response {
     pub fn new_v2_result(id: Id, result: serde_json::Value) -> Self {
         Self {
             id,
             jsonrpc: V2,
-            payload: ResponsePayload::Result(result),
+            payload: ResponsePayload::Result(
+                serde_json::value::to_raw_value(&result).expect("Value always re-serializes"),
+            ),
         }
     }
 
@@ -58,28 +140,83 @@ impl Response {
             payload: ResponsePayload::Error(error),
         }
     }
+
+    /// Checks that this response's `id` matches the `id` of the request it
+    /// is supposed to answer, returning `Error::IdMismatch` if it doesn't.
+    ///
+    /// Calling this right after deserializing a response catches a server
+    /// that replies out of order or to the wrong request, instead of
+    /// silently returning the wrong result. Nothing in this crate calls it
+    /// on a caller's behalf.
+    pub fn ensure_id_matches<C>(self, expected: &Id) -> Result<Self, Error<C>> {
+        if &self.id == expected {
+            Ok(self)
+        } else {
+            Err(Error::IdMismatch {
+                expected: expected.clone(),
+                got: self.id,
+            })
+        }
+    }
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
-#[serde(rename_all = "lowercase")]
+/// Requires serde_json's `raw_value` feature.
+///
+/// `Result` keeps the response's `result` member as unparsed JSON text
+/// instead of eagerly deserializing it into a `serde_json::Value`, so a
+/// generated method can deserialize it straight into the concrete return
+/// type (e.g. `GetBlockchainInfoResult`) without parsing it twice. The
+/// `error` member is always small and well-known, so it's still fully
+/// parsed into a [`JsonRpcError`].
+#[derive(Debug)]
 pub enum ResponsePayload {
-    Result(serde_json::Value),
+    Result(Box<serde_json::value::RawValue>),
     Error(JsonRpcError),
 }
 
+impl PartialEq for ResponsePayload {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            // Compared as parsed `Value`s rather than raw text, so two
+            // results that are semantically equal but differ in key order
+            // or whitespace still compare equal.
+            (Self::Result(a), Self::Result(b)) => {
+                let a: serde_json::Value =
+                    serde_json::from_str(a.get()).expect("RawValue is always well-formed JSON");
+                let b: serde_json::Value =
+                    serde_json::from_str(b.get()).expect("RawValue is always well-formed JSON");
+                a == b
+            }
+            (Self::Error(a), Self::Error(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
 impl From<ResponsePayload> for Result<serde_json::Value, JsonRpcError> {
     fn from(payload: ResponsePayload) -> Self {
         match payload {
-            ResponsePayload::Result(result) => Ok(result),
+            ResponsePayload::Result(result) => Ok(serde_json::from_str(result.get())
+                .expect("RawValue is always well-formed JSON")),
             ResponsePayload::Error(e) => Err(e),
         }
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct JsonRpcError {
     pub code: i64,
     pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl JsonRpcError {
+    /// Returns the [`StandardErrorCode`] for this error's `code`, or `None`
+    /// if it isn't one of the codes reserved by the JSON-RPC 2.0 spec.
+    pub fn standard_error_code(&self) -> Option<StandardErrorCode> {
+        StandardErrorCode::from_code(self.code)
+    }
 }
 
 impl fmt::Display for JsonRpcError {
@@ -88,17 +225,52 @@ impl fmt::Display for JsonRpcError {
             f,
             "JSON-RPC request failed with code {}: {}",
             self.code, self.message
-        )
+        )?;
+
+        if let Some(data) = &self.data {
+            write!(f, " ({})", data)?;
+        }
+
+        Ok(())
     }
 }
 
 impl StdError for JsonRpcError {}
 
+/// The error codes reserved by the JSON-RPC 2.0 spec, as opposed to
+/// application-defined codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StandardErrorCode {
+    ParseError,
+    InvalidRequest,
+    MethodNotFound,
+    InvalidParams,
+    InternalError,
+    ServerError(i64),
+}
+
+impl StandardErrorCode {
+    /// Maps a raw JSON-RPC error `code` to its [`StandardErrorCode`], or
+    /// `None` if `code` isn't reserved by the spec.
+    pub fn from_code(code: i64) -> Option<Self> {
+        match code {
+            -32700 => Some(Self::ParseError),
+            -32600 => Some(Self::InvalidRequest),
+            -32601 => Some(Self::MethodNotFound),
+            -32602 => Some(Self::InvalidParams),
+            -32603 => Some(Self::InternalError),
+            -32099..=-32000 => Some(Self::ServerError(code)),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Error<C> {
     Client(C),
     JsonRpc(JsonRpcError),
     Serde(serde_json::Error),
+    IdMismatch { expected: Id, got: Id },
 }
 
 impl<C> fmt::Display for Error<C>
@@ -110,6 +282,11 @@ where
             Error::Client(client_error) => fmt::Display::fmt(client_error, f),
             Error::JsonRpc(jsonrpc_error) => fmt::Display::fmt(jsonrpc_error, f),
             Error::Serde(serde_error) => fmt::Display::fmt(serde_error, f),
+            Error::IdMismatch { expected, got } => write!(
+                f,
+                "expected a response with id {:?} but got id {:?}",
+                expected, got
+            ),
         }
     }
 }
@@ -128,10 +305,113 @@ impl<C> From<JsonRpcError> for Error<C> {
 
 impl<C> StdError for Error<C> where C: StdError {}
 
+/// Produces the `id` assigned to each outgoing [`Request`]; most clients can
+/// just use [`AtomicIdGenerator`].
+pub trait IdGenerator {
+    fn next_id(&self) -> Id;
+}
+
+/// The default [`IdGenerator`]: a counter of `Id::Number`s, starting at 0.
+/// Each instance keeps its own count.
+#[derive(Debug, Default)]
+pub struct AtomicIdGenerator(std::sync::atomic::AtomicI64);
+
+impl AtomicIdGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IdGenerator for AtomicIdGenerator {
+    fn next_id(&self) -> Id {
+        Id::Number(self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed))
+    }
+}
+
 pub trait SendRequest {
     type Error: StdError;
 
     fn send_request(&self, request: Request) -> Result<Response, Self::Error>;
+
+    /// Default implementation sends each request one by one; override to do
+    /// the whole batch in a single round-trip.
+    fn send_batch(&self, requests: Vec<Request>) -> Result<Vec<Response>, Self::Error> {
+        requests
+            .into_iter()
+            .map(|request| self.send_request(request))
+            .collect()
+    }
+}
+
+/// The async counterpart to [`SendRequest`], for clients built on a
+/// non-blocking HTTP stack such as `reqwest::Client` or `hyper`.
+#[async_trait::async_trait]
+pub trait AsyncSendRequest {
+    type Error: StdError;
+
+    async fn send_request(&self, request: Request) -> Result<Response, Self::Error>;
+
+    /// Same contract as [`SendRequest::send_batch`].
+    async fn send_batch(&self, requests: Vec<Request>) -> Result<Vec<Response>, Self::Error> {
+        let mut responses = Vec::with_capacity(requests.len());
+        for request in requests {
+            responses.push(self.send_request(request).await?);
+        }
+
+        Ok(responses)
+    }
+}
+
+/// Accumulates [`Request`]s to send together through
+/// [`SendRequest::send_batch`]; [`Batch::match_responses`] then pairs each
+/// response back up by [`Id`], since servers may reply out of order.
+///
+/// A generated `BitcoindRpcBatch`-style wrapper around this builder is out
+/// of scope for this crate — it would live in `jsonrpc_client_macro`, which
+/// isn't part of this tree.
+#[derive(Debug, Default)]
+pub struct Batch {
+    requests: Vec<Request>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `request` to the batch and returns its `id`, to be used later to
+    /// find the matching response in [`Batch::match_responses`]. Returns
+    /// `None` if `request` is a notification, since the server won't send a
+    /// response to match back up.
+    pub fn push(&mut self, request: Request) -> Option<Id> {
+        let id = request.id.clone();
+        self.requests.push(request);
+
+        id
+    }
+
+    pub fn into_requests(self) -> Vec<Request> {
+        self.requests
+    }
+
+    /// Re-orders `responses` so that each entry is the response to the
+    /// correspondingly-positioned non-notification request in `requests`
+    /// (notifications are skipped, since the server must not reply to them).
+    ///
+    /// Returns `None` if any non-notification request's id has no matching
+    /// response.
+    pub fn match_responses(requests: &[Request], responses: Vec<Response>) -> Option<Vec<Response>> {
+        let mut by_id = responses
+            .into_iter()
+            .map(|response| (response.id.clone(), response))
+            .collect::<std::collections::HashMap<_, _>>();
+
+        requests
+            .iter()
+            .filter_map(|request| request.id.as_ref())
+            .map(|id| by_id.remove(id))
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -151,7 +431,8 @@ mod tests {
                 Id::String("1".to_owned()),
                 JsonRpcError {
                     code: -32601,
-                    message: "Method not found".to_owned()
+                    message: "Method not found".to_owned(),
+                    data: None,
                 }
             )
         )
@@ -166,9 +447,62 @@ mod tests {
         assert_eq!(response, Response::new_v2_result(Id::Number(1), json!(19)))
     }
 
+    #[test]
+    fn deserializes_from_a_non_static_owned_string() {
+        // A real transport hands back an owned body (`String`, `Vec<u8>`,
+        // ...), never a `&'static str` — make sure `Response` can still be
+        // deserialized from one.
+        let body: String = r#"{"jsonrpc": "2.0", "result": 19, "id": 1}"#.to_owned();
+
+        let response = serde_json::from_str::<Response>(&body).unwrap();
+
+        assert_eq!(response, Response::new_v2_result(Id::Number(1), json!(19)))
+    }
+
+    #[test]
+    fn result_deserializes_straight_into_concrete_type() {
+        #[derive(Deserialize, Debug, PartialEq)]
+        struct Block {
+            height: u32,
+            hash: String,
+        }
+
+        let json = r#"{"jsonrpc": "2.0", "result": {"height": 5, "hash": "abc"}, "id": 1}"#;
+
+        let response = serde_json::from_str::<Response>(json).unwrap();
+        let block = match response.payload {
+            ResponsePayload::Result(raw) => {
+                serde_json::from_str::<Block>(raw.get()).unwrap()
+            }
+            ResponsePayload::Error(e) => panic!("expected a result, got {:?}", e),
+        };
+
+        assert_eq!(
+            block,
+            Block {
+                height: 5,
+                hash: "abc".to_owned()
+            }
+        )
+    }
+
+    #[test]
+    fn result_equality_ignores_key_order_and_whitespace() {
+        let a = serde_json::from_str::<Response>(
+            r#"{"jsonrpc": "2.0", "result": {"height":5,"hash":"abc"}, "id": 1}"#,
+        )
+        .unwrap();
+        let b = serde_json::from_str::<Response>(
+            r#"{"jsonrpc": "2.0", "result": {"hash": "abc", "height": 5}, "id": 1}"#,
+        )
+        .unwrap();
+
+        assert_eq!(a, b)
+    }
+
     #[test]
     fn serialize_request() {
-        let request = Request::new_v2("subtract", vec![json!(42), json!(23)]);
+        let request = Request::new_v2(Id::Number(0), "subtract", vec![json!(42), json!(23)]);
 
         let json = serde_json::to_string(&request).unwrap();
 
@@ -177,4 +511,155 @@ mod tests {
             r#"{"id":0,"jsonrpc":"2.0","method":"subtract","params":[42,23]}"#
         )
     }
+
+    #[test]
+    fn serialize_notification_omits_id() {
+        let request = Request::new_v2_notification("log_level", vec![json!("debug")]);
+
+        let json = serde_json::to_string(&request).unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"jsonrpc":"2.0","method":"log_level","params":["debug"]}"#
+        )
+    }
+
+    #[test]
+    fn batch_matches_responses_out_of_order() {
+        let first_request = Request::new_v2(Id::Number(1), "first", vec![]);
+        let second_request = Request::new_v2(Id::Number(2), "second", vec![]);
+
+        let mut batch = Batch::new();
+        let first_id = batch.push(first_request).unwrap();
+        let second_id = batch.push(second_request).unwrap();
+        let requests = batch.into_requests();
+
+        let responses = vec![
+            Response::new_v2_result(second_id, json!(2)),
+            Response::new_v2_result(first_id, json!(1)),
+        ];
+
+        let matched = Batch::match_responses(&requests, responses).unwrap();
+
+        assert_eq!(
+            matched,
+            vec![
+                Response::new_v2_result(requests[0].id.clone().unwrap(), json!(1)),
+                Response::new_v2_result(requests[1].id.clone().unwrap(), json!(2)),
+            ]
+        )
+    }
+
+    #[test]
+    fn batch_skips_notifications_when_matching_responses() {
+        let call = Request::new_v2(Id::Number(1), "call", vec![]);
+        let notification = Request::new_v2_notification("notify", vec![]);
+
+        let mut batch = Batch::new();
+        let call_id = batch.push(call).unwrap();
+        assert_eq!(batch.push(notification), None);
+        let requests = batch.into_requests();
+
+        let responses = vec![Response::new_v2_result(call_id, json!(1))];
+
+        let matched = Batch::match_responses(&requests, responses).unwrap();
+
+        assert_eq!(matched, vec![Response::new_v2_result(Id::Number(1), json!(1))])
+    }
+
+    #[test]
+    fn deserialize_error_response_with_data() {
+        let json = r#"{"jsonrpc": "2.0", "error": {"code": -32602, "message": "Invalid params", "data": {"param": "height"}}, "id": 1}"#;
+
+        let response = serde_json::from_str::<Response>(json).unwrap();
+
+        assert_eq!(
+            response,
+            Response::new_v2_error(
+                Id::Number(1),
+                JsonRpcError {
+                    code: -32602,
+                    message: "Invalid params".to_owned(),
+                    data: Some(json!({"param": "height"})),
+                }
+            )
+        )
+    }
+
+    #[test]
+    fn display_includes_data_when_present() {
+        let error = JsonRpcError {
+            code: -32602,
+            message: "Invalid params".to_owned(),
+            data: Some(json!({"param": "height"})),
+        };
+
+        assert_eq!(
+            error.to_string(),
+            r#"JSON-RPC request failed with code -32602: Invalid params ({"param":"height"})"#
+        )
+    }
+
+    #[test]
+    fn serialize_named_params_request() {
+        let mut params = serde_json::Map::new();
+        params.insert("height".to_owned(), json!(5));
+        params.insert("verbose".to_owned(), json!(true));
+        let request = Request::new_v2_named(Id::Number(0), "getblock", params);
+
+        let json = serde_json::to_string(&request).unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"id":0,"jsonrpc":"2.0","method":"getblock","params":{"height":5,"verbose":true}}"#
+        )
+    }
+
+    #[test]
+    fn standard_error_code_from_code() {
+        assert_eq!(
+            StandardErrorCode::from_code(-32700),
+            Some(StandardErrorCode::ParseError)
+        );
+        assert_eq!(
+            StandardErrorCode::from_code(-32050),
+            Some(StandardErrorCode::ServerError(-32050))
+        );
+        assert_eq!(StandardErrorCode::from_code(-1), None);
+    }
+
+    #[test]
+    fn atomic_id_generator_increments() {
+        let ids = AtomicIdGenerator::new();
+
+        assert_eq!(ids.next_id(), Id::Number(0));
+        assert_eq!(ids.next_id(), Id::Number(1));
+        assert_eq!(ids.next_id(), Id::Number(2));
+    }
+
+    #[test]
+    fn response_with_matching_id_passes_through() {
+        let response = Response::new_v2_result(Id::Number(1), json!(19));
+
+        let response = response.ensure_id_matches::<std::io::Error>(&Id::Number(1));
+
+        assert_eq!(response.unwrap(), Response::new_v2_result(Id::Number(1), json!(19)));
+    }
+
+    #[test]
+    fn response_with_mismatched_id_is_rejected() {
+        let response = Response::new_v2_result(Id::Number(2), json!(19));
+
+        let error = response
+            .ensure_id_matches::<std::io::Error>(&Id::Number(1))
+            .unwrap_err();
+
+        match error {
+            Error::IdMismatch { expected, got } => {
+                assert_eq!(expected, Id::Number(1));
+                assert_eq!(got, Id::Number(2));
+            }
+            other => panic!("expected Error::IdMismatch, got {:?}", other),
+        }
+    }
 }